@@ -1,37 +1,73 @@
+extern crate aes_ctr;
 #[cfg(test)]
 extern crate anyhow;
-extern crate clear_on_drop;
-#[cfg(test)]
 extern crate data_encoding;
+extern crate hkdf;
 extern crate hmac_drbg;
+extern crate rand;
 extern crate rust_scrypt;
 extern crate serde;
 extern crate sha2;
+extern crate tiny_keccak;
 extern crate toml;
 extern crate typenum;
 extern crate unicode_segmentation;
-
-use clear_on_drop::clear::Clear;
+extern crate zeroize;
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use aes_ctr::cipher::generic_array::GenericArray;
+use aes_ctr::cipher::stream::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes128Ctr;
+use data_encoding::HEXLOWER;
+use hkdf::Hkdf;
 use hmac_drbg::HmacDRBG;
+use rand::rngs::OsRng;
+use rand::RngCore;
 use rust_scrypt::{scrypt, ScryptParams};
-use serde::Deserialize;
-use sha2::Sha512;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256, Sha512};
+use tiny_keccak::{Hasher, Keccak};
 use typenum::U64;
 use unicode_segmentation::UnicodeSegmentation;
+use zeroize::Zeroize;
 
 const CHARSET_ALPHANUMERIC: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
 
 const DEFAULT_LENGTH: usize = 24;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Password(String);
 
 impl Drop for Password {
     fn drop(&mut self) {
-        self.0.clear();
+        self.0.zeroize();
+    }
+}
+
+impl PartialEq for Password {
+    /// Constant-time comparison: unlike a derived `PartialEq`, this does not short-circuit
+    /// on the first mismatching byte, so it does not leak how many leading characters match.
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(self.0.as_bytes(), other.0.as_bytes())
     }
 }
 
+const HKDF_V1_NAMESPACE: &[u8] = b"pwclip/v1";
+
+/// Selects how a [`PWM`] turns a [`Key`] into a [`Password`].
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Version {
+    /// The original `HmacDRBG`-based derivation, kept for passwords already in use.
+    #[default]
+    Legacy,
+    /// HKDF-SHA512 with length-prefixed, namespaced inputs; see `password_raw_v1`.
+    V1,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(default)]
 pub struct PWM {
@@ -41,6 +77,7 @@ pub struct PWM {
     prefix: String,
     charset: String,
     length: usize,
+    version: Version,
 }
 
 impl Default for PWM {
@@ -52,67 +89,537 @@ impl Default for PWM {
             prefix: Default::default(),
             charset: CHARSET_ALPHANUMERIC.to_string(),
             length: DEFAULT_LENGTH,
+            version: Default::default(),
+        }
+    }
+}
+
+/// Fills `charset`-grapheme characters from `bytes` by rejection sampling, discarding
+/// the high, non-uniform remainder of the byte range before reducing modulo the charset.
+fn fill_from_charset(
+    prefix: &str,
+    charset: &str,
+    length: usize,
+    bytes: impl Iterator<Item = u8>,
+) -> Password {
+    let charset_graphemes: Vec<&str> = charset.graphemes(true).collect();
+    let charset_len: usize = charset_graphemes.len();
+    let chars: String = bytes
+        .filter(|r| (*r as usize) < 256 - (256 % charset_len))
+        .map(|r| charset_graphemes[r as usize % charset_len])
+        .take(length - prefix.len())
+        .collect();
+
+    let mut password: String = prefix.to_owned();
+    password.push_str(&chars);
+    Password(password)
+}
+
+/// A stream of HKDF-Expand output, chaining further expand calls (each
+/// distinguished by an incrementing counter appended to `info`) once one is exhausted.
+/// The `u8` counter allows 255 blocks of 64 bytes; asking for more panics.
+struct HkdfStream<'a> {
+    hkdf: &'a Hkdf<Sha512>,
+    info: Vec<u8>,
+    counter: u8,
+    block: [u8; 64],
+    pos: usize,
+}
+
+impl<'a> HkdfStream<'a> {
+    fn new(hkdf: &'a Hkdf<Sha512>, info: Vec<u8>) -> Self {
+        HkdfStream {
+            hkdf,
+            info,
+            counter: 0,
+            block: [0u8; 64],
+            pos: 64,
+        }
+    }
+}
+
+impl<'a> Iterator for HkdfStream<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.block.len() {
+            let mut info = self.info.clone();
+            if self.counter > 0 {
+                info.push(self.counter);
+            }
+            self.hkdf
+                .expand(&info, &mut self.block)
+                .expect("hkdf expand output length is valid");
+            self.counter = self
+                .counter
+                .checked_add(1)
+                .expect("exhausted hkdf expand blocks");
+            self.pos = 0;
         }
+        let byte = self.block[self.pos];
+        self.pos += 1;
+        Some(byte)
     }
 }
 
 impl PWM {
-    fn password_raw(&self, key: &[u8]) -> Password {
+    fn hkdf_v1_info(&self) -> Vec<u8> {
+        let mut info = HKDF_V1_NAMESPACE.to_vec();
+        for field in &[
+            self.url.as_str(),
+            self.username.as_str(),
+            self.extra.as_deref().unwrap_or(""),
+        ] {
+            let bytes = field.as_bytes();
+            info.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            info.extend_from_slice(bytes);
+        }
+        info
+    }
+
+    fn password_raw_legacy(&self, key: &[u8]) -> Password {
         let mut drbg = HmacDRBG::<Sha512>::new(key, &[], &[]);
         drbg.reseed(self.url.as_bytes(), None);
         drbg.reseed(self.username.as_bytes(), None);
         if let Some(ref extra) = self.extra {
             drbg.reseed(extra.as_bytes(), None);
         }
+        fill_from_charset(
+            &self.prefix,
+            &self.charset,
+            self.length,
+            drbg.generate::<U64>(None).into_iter(),
+        )
+    }
 
-        let chars: String = {
-            let charset_graphemes: Vec<&str> = self.charset.graphemes(true).collect();
-            let charset_len: usize = charset_graphemes.len();
-            drbg.generate::<U64>(None)
-                .into_iter()
-                .filter(|r| (*r as usize) < 256 - (256 % charset_len))
-                .map(|r| charset_graphemes[r as usize % charset_len])
-                .take(self.length - self.prefix.len())
-                .collect()
-        };
+    fn password_raw_v1(&self, key: &[u8]) -> Password {
+        let hkdf = Hkdf::<Sha512>::new(Some(&[]), key);
+        let stream = HkdfStream::new(&hkdf, self.hkdf_v1_info());
+        fill_from_charset(&self.prefix, &self.charset, self.length, stream)
+    }
 
-        let mut password: String = self.prefix.to_owned();
-        password.push_str(&chars);
-        Password(password)
+    fn password_raw(&self, key: &[u8]) -> Password {
+        match self.version {
+            Version::Legacy => self.password_raw_legacy(key),
+            Version::V1 => self.password_raw_v1(key),
+        }
     }
 
     pub fn password(&self, key: Key) -> Password {
-        self.password_raw(&key.0)
+        self.password_raw(key.0.as_bytes())
+    }
+}
+
+/// A fixed-size byte buffer that zeroizes itself on drop, backing [`Key`].
+struct SecretBytes<const N: usize>([u8; N]);
+
+impl<const N: usize> SecretBytes<N> {
+    fn new(bytes: [u8; N]) -> Self {
+        SecretBytes(bytes)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn into_inner(self) -> [u8; N] {
+        self.0
+    }
+}
+
+impl<const N: usize> Zeroize for SecretBytes<N> {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<const N: usize> Drop for SecretBytes<N> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl<const N: usize> fmt::Debug for SecretBytes<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretBytes(..)")
     }
 }
 
 #[derive(Debug)]
-pub struct Key([u8; 32]);
+pub struct Key(SecretBytes<32>);
 
 impl Key {
-    pub fn new(passphrase: &[u8]) -> Key {
-        let params = ScryptParams::new(2 << 15, 8, 1);
+    pub fn new(passphrase: &[u8], params: &KdfParams) -> Key {
         let mut buf = [0u8; 32];
-        scrypt(passphrase, b"pwclip", &params, &mut buf);
-        Key(buf)
+        scrypt(
+            passphrase,
+            b"pwclip",
+            &ScryptParams::new(params.n, params.r, params.p),
+            &mut buf,
+        );
+        Key(SecretBytes::new(buf))
     }
 }
 
 impl From<Key> for [u8; 32] {
     fn from(key: Key) -> [u8; 32] {
-        key.0
+        key.0.into_inner()
     }
 }
 
 impl From<Key> for Vec<u8> {
     fn from(key: Key) -> Vec<u8> {
-        key.0.to_vec()
+        key.0.as_bytes().to_vec()
     }
 }
 
-impl Drop for Key {
-    fn drop(&mut self) {
-        self.0.clear();
+impl Key {
+    /// Encrypts the key to the Ethereum keystore v3 format, locked by `password`.
+    pub fn encrypt(&self, password: &[u8]) -> Keystore {
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut iv);
+
+        let kdfparams = KeystoreKdfParams {
+            kdf: KdfParams::default(),
+            dklen: 32,
+            salt: HexBytes(salt.to_vec()),
+        };
+        let mut dk = [0u8; 32];
+        scrypt(
+            password,
+            &kdfparams.salt.0,
+            &ScryptParams::new(kdfparams.kdf.n, kdfparams.kdf.r, kdfparams.kdf.p),
+            &mut dk,
+        );
+
+        let mut ciphertext = self.0.as_bytes().to_vec();
+        let mut cipher = Aes128Ctr::new(
+            GenericArray::from_slice(&dk[0..16]),
+            GenericArray::from_slice(&iv),
+        );
+        cipher.apply_keystream(&mut ciphertext);
+
+        let mac = keccak256(&[&dk[16..32], &ciphertext[..]].concat());
+        dk.zeroize();
+
+        Keystore {
+            version: 3,
+            crypto: Crypto {
+                cipher: "aes-128-ctr".to_string(),
+                cipherparams: CipherParams {
+                    iv: HexBytes(iv.to_vec()),
+                },
+                ciphertext: HexBytes(ciphertext),
+                kdf: "scrypt".to_string(),
+                kdfparams,
+                mac: HexBytes(mac.to_vec()),
+            },
+        }
+    }
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Constant-time byte comparison, used to avoid leaking MAC-matching progress via timing.
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+const BIP39_WORDLIST_RAW: &str = include_str!("bip39_english.txt");
+
+fn bip39_wordlist() -> Vec<&'static str> {
+    BIP39_WORDLIST_RAW.lines().collect()
+}
+
+fn bits_of(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+        .collect()
+}
+
+fn bits_to_index(bits: &[bool]) -> usize {
+    bits.iter()
+        .fold(0usize, |acc, &bit| (acc << 1) | bit as usize)
+}
+
+impl Key {
+    /// Generates a fresh `Key` from `entropy_bits` bits of CSPRNG entropy, returning it
+    /// alongside the recovery phrase that can later reproduce it via [`Key::from_mnemonic`].
+    ///
+    /// `entropy_bits` must be one of 128, 160, 192, 224, or 256, per BIP-39.
+    pub fn generate_mnemonic(entropy_bits: usize) -> (String, Key) {
+        assert!(
+            matches!(entropy_bits, 128 | 160 | 192 | 224 | 256),
+            "entropy_bits must be one of 128, 160, 192, 224, 256"
+        );
+        let mut entropy = vec![0u8; entropy_bits / 8];
+        OsRng.fill_bytes(&mut entropy);
+        let phrase = mnemonic_encode(&entropy);
+        let key = Key::new(&entropy, &KdfParams::default());
+        entropy.zeroize();
+        (phrase, key)
+    }
+
+    /// Recovers the `Key` backed by a BIP-39 recovery phrase, verifying its checksum.
+    pub fn from_mnemonic(words: &str) -> Result<Key, Error> {
+        let mut entropy = mnemonic_decode(words)?;
+        let key = Key::new(&entropy, &KdfParams::default());
+        entropy.zeroize();
+        Ok(key)
+    }
+}
+
+fn mnemonic_encode(entropy: &[u8]) -> String {
+    let checksum_bits = entropy.len() * 8 / 32;
+    let hash = Sha256::digest(entropy);
+
+    let mut bits = bits_of(entropy);
+    bits.extend(bits_of(&hash).into_iter().take(checksum_bits));
+
+    let wordlist = bip39_wordlist();
+    bits.chunks(11)
+        .map(|chunk| wordlist[bits_to_index(chunk)])
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn mnemonic_decode(phrase: &str) -> Result<Vec<u8>, Error> {
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if ![12, 15, 18, 21, 24].contains(&words.len()) {
+        return Err(Error::Mnemonic);
+    }
+
+    let indices: HashMap<&str, usize> = bip39_wordlist()
+        .into_iter()
+        .enumerate()
+        .map(|(i, w)| (w, i))
+        .collect();
+
+    let mut bits = Vec::with_capacity(words.len() * 11);
+    for word in words {
+        let index = *indices.get(word).ok_or(Error::Mnemonic)?;
+        for i in (0..11).rev() {
+            bits.push((index >> i) & 1 == 1);
+        }
+    }
+
+    let checksum_bits = bits.len() / 33;
+    let entropy_bits = bits.len() - checksum_bits;
+    let entropy: Vec<u8> = bits[..entropy_bits]
+        .chunks(8)
+        .map(|chunk| chunk.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+        .collect();
+
+    let expected_checksum = bits_of(&Sha256::digest(&entropy));
+    if bits[entropy_bits..] != expected_checksum[..checksum_bits] {
+        return Err(Error::Mnemonic);
+    }
+
+    Ok(entropy)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Mac,
+    Ciphertext,
+    Mnemonic,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Mac => write!(f, "keystore MAC does not match"),
+            Error::Ciphertext => write!(f, "keystore ciphertext is malformed"),
+            Error::Mnemonic => write!(f, "mnemonic phrase is invalid or has a bad checksum"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Debug, Clone)]
+struct HexBytes(Vec<u8>);
+
+impl Serialize for HexBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&HEXLOWER.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        HEXLOWER
+            .decode(s.as_bytes())
+            .map(HexBytes)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Cost parameters to the scrypt KDF.
+///
+/// The defaults match pwclip's historical hardcoded values; changing them for an
+/// already-derived [`Key`] changes the key, so persist whatever `KdfParams` were
+/// used to reproduce it later.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub n: u64,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            n: 2 << 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// Upper bound on the scrypt cost parameters accepted from an untrusted keystore.
+///
+/// scrypt's memory use is roughly `128 * n * r` bytes, so without a ceiling a
+/// malicious keystore could set `n`/`r` high enough to exhaust memory before
+/// `Keystore::decrypt` ever gets to check the MAC.
+const MAX_KDF_N: u64 = 1 << 20;
+const MAX_KDF_R: u32 = 16;
+const MAX_KDF_P: u32 = 16;
+
+impl KdfParams {
+    /// Rejects cost parameters outside the accepted range (which `calibrate` can exceed):
+    /// `n` must be a power of two in `2..=MAX_KDF_N`, and `r`/`p` must be in
+    /// `1..=MAX_KDF_R`/`MAX_KDF_P`.
+    fn is_sane(&self) -> bool {
+        self.n.is_power_of_two()
+            && (2..=MAX_KDF_N).contains(&self.n)
+            && (1..=MAX_KDF_R).contains(&self.r)
+            && (1..=MAX_KDF_P).contains(&self.p)
+    }
+
+    /// Finds the largest power-of-two `n` whose derivation stays under `target`,
+    /// starting at the smallest valid cost (`n = 2`) and doubling until a trial
+    /// run exceeds it or `n` can no longer be doubled without overflow. `n = 2` itself
+    /// is never timed, so it is returned even when it does not stay under `target`.
+    pub fn calibrate(target: Duration) -> KdfParams {
+        let Self { r, p, .. } = KdfParams::default();
+        let mut best = KdfParams { n: 2, r, p };
+        loop {
+            let next_n = match best.n.checked_mul(2) {
+                Some(n) => n,
+                None => return best,
+            };
+            let candidate = KdfParams { n: next_n, r, p };
+            let mut buf = [0u8; 32];
+            let start = Instant::now();
+            scrypt(
+                b"",
+                b"pwclip",
+                &ScryptParams::new(candidate.n, candidate.r, candidate.p),
+                &mut buf,
+            );
+            if start.elapsed() >= target {
+                return best;
+            }
+            best = candidate;
+        }
+    }
+}
+
+/// The scrypt parameters and salt stored alongside an encrypted [`Keystore`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeystoreKdfParams {
+    #[serde(flatten)]
+    kdf: KdfParams,
+    dklen: usize,
+    salt: HexBytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CipherParams {
+    iv: HexBytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Crypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: HexBytes,
+    kdf: String,
+    kdfparams: KeystoreKdfParams,
+    mac: HexBytes,
+}
+
+/// An encrypted [`Key`], serialized in the Ethereum keystore v3 JSON layout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    version: u8,
+    crypto: Crypto,
+}
+
+impl Keystore {
+    pub fn decrypt(&self, password: &[u8]) -> Result<Key, Error> {
+        if self.version != 3 || self.crypto.cipher != "aes-128-ctr" || self.crypto.kdf != "scrypt" {
+            return Err(Error::Ciphertext);
+        }
+
+        let kdfparams = &self.crypto.kdfparams;
+        if kdfparams.dklen != 32 || !kdfparams.kdf.is_sane() {
+            return Err(Error::Ciphertext);
+        }
+        let mut dk = vec![0u8; kdfparams.dklen];
+        scrypt(
+            password,
+            &kdfparams.salt.0,
+            &ScryptParams::new(kdfparams.kdf.n, kdfparams.kdf.r, kdfparams.kdf.p),
+            &mut dk,
+        );
+
+        let mac = keccak256(&[&dk[16..32], &self.crypto.ciphertext.0[..]].concat());
+        if !ct_eq(&mac, &self.crypto.mac.0) {
+            dk.zeroize();
+            return Err(Error::Mac);
+        }
+
+        if self.crypto.cipherparams.iv.0.len() != 16 || self.crypto.ciphertext.0.len() != 32 {
+            dk.zeroize();
+            return Err(Error::Ciphertext);
+        }
+
+        let mut plaintext = self.crypto.ciphertext.0.clone();
+        let mut cipher = Aes128Ctr::new(
+            GenericArray::from_slice(&dk[0..16]),
+            GenericArray::from_slice(&self.crypto.cipherparams.iv.0),
+        );
+        cipher.apply_keystream(&mut plaintext);
+        dk.zeroize();
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&plaintext);
+        plaintext.zeroize();
+        Ok(Key(SecretBytes::new(key)))
     }
 }
 
@@ -251,6 +758,49 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_passwords_v1() {
+        let password_tests = [Test {
+            pwm: PWM {
+                url: test_url!(),
+                username: test_username!(),
+                length: 32,
+                version: Version::V1,
+                ..Default::default()
+            },
+            pws: [
+                "4pb2d8Nk0TEBr05YRCQCTQFiLDDUwC3b",
+                "5UTnaD7nT13tth8TyOX0xJsuIjg0xFP6",
+            ],
+        }];
+
+        for test in password_tests.iter() {
+            for (k, pw) in test.pws.iter().enumerate() {
+                let expected = Password(pw.to_string());
+                let actual = test.pwm.password_raw(PASSWORD_TEST_KEYS[k].as_bytes());
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn test_password_equality() {
+        assert_eq!(
+            Password("s3cr3t".to_string()),
+            Password("s3cr3t".to_string())
+        );
+        assert_ne!(
+            Password("s3cr3t".to_string()),
+            Password("s3cr3u".to_string())
+        );
+        assert_ne!(
+            Password("s3cr3t".to_string()),
+            Password("s3cr3".to_string())
+        );
+        assert_ne!(Password("".to_string()), Password("s3cr3t".to_string()));
+        assert_eq!(Password("".to_string()), Password("".to_string()));
+    }
+
     #[test]
     fn test_keys() -> Result<()> {
         let key_tests = [
@@ -294,13 +844,132 @@ mod test {
 
         for test in key_tests.iter() {
             let expected = HEXLOWER.decode(test.keyhex)?;
-            let actual: Vec<u8> = Key::new(test.passphrase).into();
+            let actual: Vec<u8> = Key::new(test.passphrase, &KdfParams::default()).into();
             assert_eq!(expected, actual);
         }
 
         return Ok(());
     }
 
+    #[test]
+    fn test_keystore_roundtrip() {
+        let expected: Vec<u8> = Key::new(b"keystore test passphrase", &KdfParams::default()).into();
+        let key = Key::new(b"keystore test passphrase", &KdfParams::default());
+
+        let keystore = key.encrypt(b"correct horse battery staple");
+        let decrypted = keystore.decrypt(b"correct horse battery staple").unwrap();
+        assert_eq!(expected, Vec::<u8>::from(decrypted));
+    }
+
+    #[test]
+    fn test_keystore_rejects_wrong_password() {
+        let key = Key::new(b"keystore test passphrase", &KdfParams::default());
+        let keystore = key.encrypt(b"correct horse battery staple");
+        assert!(matches!(
+            keystore.decrypt(b"wrong password"),
+            Err(Error::Mac)
+        ));
+    }
+
+    #[test]
+    fn test_keystore_rejects_tampered_ciphertext() {
+        let key = Key::new(b"keystore test passphrase", &KdfParams::default());
+        let mut keystore = key.encrypt(b"correct horse battery staple");
+        keystore.crypto.ciphertext.0[0] ^= 0xff;
+        assert!(matches!(
+            keystore.decrypt(b"correct horse battery staple"),
+            Err(Error::Mac)
+        ));
+    }
+
+    #[test]
+    fn test_keystore_rejects_oversized_kdf_params() {
+        let key = Key::new(b"keystore test passphrase", &KdfParams::default());
+
+        let mut huge_n = key.encrypt(b"correct horse battery staple");
+        huge_n.crypto.kdfparams.kdf.n = MAX_KDF_N * 2;
+        assert!(matches!(
+            huge_n.decrypt(b"correct horse battery staple"),
+            Err(Error::Ciphertext)
+        ));
+
+        let mut non_power_of_two = key.encrypt(b"correct horse battery staple");
+        non_power_of_two.crypto.kdfparams.kdf.n = 3;
+        assert!(matches!(
+            non_power_of_two.decrypt(b"correct horse battery staple"),
+            Err(Error::Ciphertext)
+        ));
+    }
+
+    struct MnemonicTest<'a> {
+        entropy: &'a [u8],
+        phrase: &'a str,
+    }
+
+    #[test]
+    fn test_mnemonic_vectors() {
+        let mnemonic_tests = [
+            MnemonicTest {
+                entropy: &[0u8; 16],
+                phrase: "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon about",
+            },
+            MnemonicTest {
+                entropy: &[0xffu8; 32],
+                phrase: "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo \
+                          zoo zoo zoo zoo zoo vote",
+            },
+            MnemonicTest {
+                entropy: &[
+                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
+                    0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13,
+                ],
+                phrase: "abandon amount liar amount expire adjust cage candy arch gather drum \
+                          bullet absurd math exhibit",
+            },
+        ];
+
+        for test in mnemonic_tests.iter() {
+            assert_eq!(mnemonic_encode(test.entropy), test.phrase);
+            assert_eq!(mnemonic_decode(test.phrase).unwrap(), test.entropy);
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_roundtrip() {
+        for &bits in &[128usize, 160, 192, 224, 256] {
+            let (phrase, key) = Key::generate_mnemonic(bits);
+            let recovered = Key::from_mnemonic(&phrase).unwrap();
+            assert_eq!(Vec::<u8>::from(key), Vec::<u8>::from(recovered));
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_rejects_bad_checksum() {
+        assert!(matches!(
+            Key::from_mnemonic(
+                "abandon zoo abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon about"
+            ),
+            Err(Error::Mnemonic)
+        ));
+    }
+
+    #[test]
+    fn test_mnemonic_rejects_malformed_phrase() {
+        assert!(matches!(
+            Key::from_mnemonic("abandon abandon"),
+            Err(Error::Mnemonic)
+        ));
+        assert!(matches!(
+            Key::from_mnemonic(
+                "notaword abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon about"
+            ),
+            Err(Error::Mnemonic)
+        ));
+    }
+
     #[test]
     fn construct_pwm_test() -> Result<()> {
         let config = r#"